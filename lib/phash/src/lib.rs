@@ -0,0 +1,126 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Runtime-side lookup tables baked into `kconfig.rs` by `sys/kern/build.rs`.
+//!
+//! Each type here is a thin, `const`-constructible wrapper around the
+//! slices `build.rs` emits as literals; the matching `phash_gen` crate
+//! builds the `m`/`g`/value data these wrap. Lookups never allocate and
+//! never fail at runtime on well-formed input: every slot `build.rs` didn't
+//! fill with a real key is filled with that key type's `invalid()` sentinel
+//! instead of `None`, so a miss just falls out of the equality check below.
+
+#![no_std]
+
+/// A key usable in one of this crate's hash tables.
+///
+/// Kernel lookup keys are small, fixed-width ABI types, so a dedicated
+/// trait -- rather than `core::hash::Hash` plus a `Hasher` -- lets
+/// `phash_gen` reuse, bit-for-bit, the exact same hash family at build time
+/// that this crate uses at lookup time.
+pub trait PerfectHashKey {
+    /// A hash of `self` for the given `level`. `level == 0` picks a bucket;
+    /// [`NestedPerfectHashMap`] and [`ChdMap`] additionally use
+    /// `level == d + 1` for each candidate displacement `d` their builder
+    /// tried.
+    fn phash(&self, level: u64) -> u64;
+}
+
+/// Reduces `key.phash(level)` into `0..modulus`, doing the modulo in `u64`
+/// before narrowing to `usize` so the result doesn't depend on whether
+/// `usize` is 32 or 64 bits wide -- this crate and `phash_gen` run on
+/// different targets and must agree on every slot index.
+fn slot<K: PerfectHashKey>(key: &K, level: u64, modulus: usize) -> usize {
+    (key.phash(level) % modulus as u64) as usize
+}
+
+/// A table searched with a binary search over its sorted keys.
+///
+/// Used on targets (Cortex-M0 / thumbv6m) too small to spend code size on
+/// perfect-hash machinery.
+pub struct SortedList<K: 'static, V: 'static> {
+    pub values: &'static [(K, V)],
+}
+
+impl<K: Ord + 'static, V: 'static> SortedList<K, V> {
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.values
+            .binary_search_by(|(k, _)| k.cmp(key))
+            .ok()
+            .map(|i| &self.values[i].1)
+    }
+}
+
+/// A single-level minimal perfect hash: `values[h0(key) mod m]`.
+pub struct PerfectHashMap<'a, K, V> {
+    pub m: usize,
+    pub values: &'a [(K, V)],
+}
+
+impl<'a, K: PerfectHashKey + PartialEq, V> PerfectHashMap<'a, K, V> {
+    pub fn get(&self, key: &K) -> Option<&V> {
+        // An empty table is emitted with `m == 0`; there's no slot to probe.
+        if self.m == 0 {
+            return None;
+        }
+        let (k, v) = &self.values[slot(key, 0, self.m)];
+        if k == key {
+            Some(v)
+        } else {
+            None
+        }
+    }
+}
+
+/// A two-level perfect hash: `h0(key) mod m` picks a bucket, and
+/// `g[bucket]` is the displacement that bucket's own small perfect-hash
+/// table was built with.
+pub struct NestedPerfectHashMap<'a, K, V> {
+    pub m: usize,
+    pub g: &'a [u32],
+    pub values: &'a [&'a [(K, V)]],
+}
+
+impl<'a, K: PerfectHashKey + PartialEq, V> NestedPerfectHashMap<'a, K, V> {
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let bucket = slot(key, 0, self.m);
+        let inner = self.values[bucket];
+        if inner.is_empty() {
+            return None;
+        }
+        let (k, v) = &inner[slot(key, self.g[bucket] as u64 + 1, inner.len())];
+        if k == key {
+            Some(v)
+        } else {
+            None
+        }
+    }
+}
+
+/// A CHD ("compress, hash and displace") minimal perfect hash: `h0(key) mod
+/// m` assigns a key to one of `m` buckets, and `g[bucket]` is the
+/// displacement that bucket was given so every key across every bucket
+/// lands in a distinct slot of the single, shared, `r`-sized `values`
+/// array. Typically costs only ~2 bits/key of `g`, far less than
+/// [`NestedPerfectHashMap`]'s per-bucket table, at the cost of a fussier
+/// build-time search.
+pub struct ChdMap<'a, K, V> {
+    pub m: usize,
+    pub r: usize,
+    pub g: &'a [u32],
+    pub values: &'a [(K, V)],
+}
+
+impl<'a, K: PerfectHashKey + PartialEq, V> ChdMap<'a, K, V> {
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let bucket = slot(key, 0, self.m);
+        let d = self.g[bucket] as u64;
+        let (k, v) = &self.values[slot(key, d + 1, self.r)];
+        if k == key {
+            Some(v)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,345 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Build-time construction of the lookup tables `phash` reads at runtime.
+//!
+//! Each `Owned*` type here mirrors one of `phash`'s borrowed, `const`-ready
+//! types, but owns its data so `sys/kern/build.rs` can inspect it (to pick
+//! the smallest table) and format it into `kconfig.rs` before a single
+//! `&'static` ever exists. Every builder takes an explicit `seed`: given the
+//! same seed and the same input keys/values, each one always produces
+//! byte-identical output, which is what makes the kernel images `build.rs`
+//! generates bit-reproducible.
+
+use anyhow::{bail, Result};
+use phash::PerfectHashKey;
+
+/// A tiny, fixed, non-cryptographic PRNG used only to make the bucket
+/// tie-breaks below reproducible from a seed -- never for anything
+/// security-sensitive.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// Seeded Fisher-Yates shuffle, used to fix the order in which buckets are
+/// processed from a seed (see [`OwnedChdMap::build`], where it decides
+/// ties between buckets of equal size, and
+/// [`OwnedNestedPerfectHashMap::build`]).
+fn shuffle<T>(items: &mut [T], seed: u64) {
+    let mut rng = SplitMix64(seed);
+    for i in (1..items.len()).rev() {
+        let j = (rng.next() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+fn slot<K: PerfectHashKey>(key: &K, level: u64, modulus: usize) -> usize {
+    (key.phash(level) % modulus as u64) as usize
+}
+
+/// Builder for [`phash::SortedList`].
+#[derive(Debug)]
+pub struct OwnedSortedList<K, V> {
+    pub values: Vec<(K, V)>,
+}
+
+impl<K: Ord, V> OwnedSortedList<K, V> {
+    /// Sorting is already fully determined by the input keys, so `seed`
+    /// goes unused here; it's threaded through for a uniform signature with
+    /// the perfect-hash builders below, whose searches do depend on it.
+    pub fn build(mut items: Vec<(K, V)>, _seed: u64) -> Result<Self> {
+        items.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(Self { values: items })
+    }
+}
+
+/// Builder for [`phash::PerfectHashMap`].
+#[derive(Debug)]
+pub struct OwnedPerfectHashMap<K, V> {
+    pub m: usize,
+    pub values: Vec<Option<(K, V)>>,
+}
+
+impl<K, V> OwnedPerfectHashMap<K, V>
+where
+    K: PerfectHashKey + PartialEq + Clone,
+    V: Clone,
+{
+    /// How much slack over `n` this builder will try before giving up and
+    /// letting the caller fall back to [`OwnedNestedPerfectHashMap`] or
+    /// [`OwnedChdMap`]. A single-level perfect hash with no slack at all
+    /// gets exponentially less likely to exist as `n` grows, so there's no
+    /// point searching forever for one.
+    const MAX_SLACK: usize = 8;
+
+    /// `seed` goes unused here: this builder's probe order (`m = n, n+1,
+    /// …`) is already fully determined by `n`, with no tie to break.
+    pub fn build(items: Vec<(K, V)>, _seed: u64) -> Result<Self> {
+        let n = items.len();
+        if n == 0 {
+            return Ok(Self { m: 0, values: vec![] });
+        }
+        for slack in 0..Self::MAX_SLACK {
+            let m = n + slack;
+            let mut values: Vec<Option<(K, V)>> = vec![None; m];
+            let mut collided = false;
+            for (k, v) in &items {
+                let s = slot(k, 0, m);
+                if values[s].is_some() {
+                    collided = true;
+                    break;
+                }
+                values[s] = Some((k.clone(), v.clone()));
+            }
+            if !collided {
+                return Ok(Self { m, values });
+            }
+        }
+        bail!(
+            "no single-level perfect hash found for {} keys within {} slots \
+            of slack",
+            n,
+            Self::MAX_SLACK,
+        );
+    }
+}
+
+/// Builder for [`phash::NestedPerfectHashMap`].
+#[derive(Debug)]
+pub struct OwnedNestedPerfectHashMap<K, V> {
+    pub m: usize,
+    pub g: Vec<u32>,
+    pub values: Vec<Vec<Option<(K, V)>>>,
+}
+
+impl<K, V> OwnedNestedPerfectHashMap<K, V>
+where
+    K: PerfectHashKey + PartialEq + Clone,
+    V: Clone,
+{
+    /// Target average bucket size: small enough that each bucket's inner
+    /// displacement search below terminates quickly, large enough that `m`
+    /// -- and so `g` -- stays small.
+    const LOAD_FACTOR: usize = 4;
+    /// Displacements above this are vanishingly unlikely for the table
+    /// sizes this crate builds; treat hitting it as a bug, not as "search
+    /// longer".
+    const MAX_DISPLACEMENT: u32 = 10_000;
+
+    pub fn build(items: Vec<(K, V)>, seed: u64) -> Result<Self> {
+        let n = items.len();
+        let m = std::cmp::max(1, (n + Self::LOAD_FACTOR - 1) / Self::LOAD_FACTOR);
+
+        let mut buckets: Vec<Vec<(K, V)>> = vec![vec![]; m];
+        for (k, v) in items {
+            let b = slot(&k, 0, m);
+            buckets[b].push((k, v));
+        }
+
+        // Each bucket's displacement search is independent of every other
+        // bucket's, and slots are picked by hash alone, so the output
+        // depends on neither this processing order nor the order `items`
+        // arrived in; the seeded shuffle only fixes the order the searches
+        // run in.
+        let mut order: Vec<usize> = (0..m).collect();
+        shuffle(&mut order, seed);
+
+        let mut g = vec![0u32; m];
+        let mut values: Vec<Vec<Option<(K, V)>>> = vec![vec![]; m];
+        for bucket in order {
+            let keys = &buckets[bucket];
+            if keys.is_empty() {
+                continue;
+            }
+            let r = keys.len();
+            let mut placed = false;
+            'displacement: for d in 0..Self::MAX_DISPLACEMENT {
+                let mut slots: Vec<Option<(K, V)>> = vec![None; r];
+                for (k, v) in keys {
+                    let s = slot(k, d as u64 + 1, r);
+                    if slots[s].is_some() {
+                        continue 'displacement;
+                    }
+                    slots[s] = Some((k.clone(), v.clone()));
+                }
+                g[bucket] = d;
+                values[bucket] = slots;
+                placed = true;
+                break;
+            }
+            if !placed {
+                bail!(
+                    "no displacement under {} found for bucket {} ({} keys)",
+                    Self::MAX_DISPLACEMENT,
+                    bucket,
+                    r,
+                );
+            }
+        }
+
+        Ok(Self { m, g, values })
+    }
+}
+
+/// Builder for [`phash::ChdMap`], implementing the CHD ("compress, hash and
+/// displace") algorithm: `m ≈ n / LOAD_FACTOR` buckets are assigned by
+/// `h0(key) mod m`, then resolved largest-first by searching displacements
+/// `d = 0, 1, 2, …` until `h_d(key) mod r` places every key in that bucket
+/// into a still-free slot of the single, shared, `r`-slot output array.
+#[derive(Debug)]
+pub struct OwnedChdMap<K, V> {
+    pub m: usize,
+    pub r: usize,
+    pub g: Vec<u32>,
+    pub values: Vec<Option<(K, V)>>,
+}
+
+impl<K, V> OwnedChdMap<K, V>
+where
+    K: PerfectHashKey + PartialEq + Clone,
+    V: Clone,
+{
+    const LOAD_FACTOR: usize = 4;
+    const MAX_DISPLACEMENT: u32 = 10_000;
+
+    pub fn build(items: Vec<(K, V)>, seed: u64) -> Result<Self> {
+        let n = items.len();
+        let m = std::cmp::max(1, (n + Self::LOAD_FACTOR - 1) / Self::LOAD_FACTOR);
+        let r = std::cmp::max(1, n);
+
+        let mut buckets: Vec<Vec<(K, V)>> = vec![vec![]; m];
+        for (k, v) in items {
+            let b = slot(&k, 0, m);
+            buckets[b].push((k, v));
+        }
+
+        // Largest-first: a big bucket has far fewer displacements that
+        // avoid every slot smaller buckets have already claimed, so giving
+        // it first pick of the shared `r` slots is what makes the search
+        // converge at all. Buckets of equal size are order-independent for
+        // correctness; the seeded shuffle before the (stable) sort just
+        // keeps that tie-break reproducible instead of depending on
+        // incidental bucket-index order.
+        let mut order: Vec<usize> = (0..m).collect();
+        shuffle(&mut order, seed);
+        order.sort_by_key(|&b| std::cmp::Reverse(buckets[b].len()));
+
+        let mut g = vec![0u32; m];
+        let mut values: Vec<Option<(K, V)>> = vec![None; r];
+        for bucket in order {
+            let keys = &buckets[bucket];
+            if keys.is_empty() {
+                continue;
+            }
+            let mut placed = false;
+            'displacement: for d in 0..Self::MAX_DISPLACEMENT {
+                let slots: Vec<usize> =
+                    keys.iter().map(|(k, _)| slot(k, d as u64 + 1, r)).collect();
+                for (i, &s) in slots.iter().enumerate() {
+                    if values[s].is_some() || slots[..i].contains(&s) {
+                        continue 'displacement;
+                    }
+                }
+                for (&s, (k, v)) in slots.iter().zip(keys) {
+                    values[s] = Some((k.clone(), v.clone()));
+                }
+                g[bucket] = d;
+                placed = true;
+                break;
+            }
+            if !placed {
+                bail!(
+                    "no displacement under {} found for CHD bucket {} ({} keys)",
+                    Self::MAX_DISPLACEMENT,
+                    bucket,
+                    keys.len(),
+                );
+            }
+        }
+
+        let built = Self { m, r, g, values };
+
+        // Construction above only checks that no key lands on a slot that
+        // is already taken; it never reads a key back out afterward. Do that now, the same way `phash::ChdMap::get` will at
+        // runtime, so a bug in the displacement search that still happens
+        // to avoid collisions (e.g. placing a key at the wrong bucket's
+        // slot) fails the build instead of shipping a table some keys can't
+        // look up.
+        for (k, _) in buckets.into_iter().flatten() {
+            if built.get(&k).is_none() {
+                bail!("CHD round-trip check failed: a key was not found in the table after construction");
+            }
+        }
+
+        Ok(built)
+    }
+
+    /// Looks up `key`, exactly the way `phash::ChdMap::get` does at
+    /// runtime. Used above to prove every input key round-trips through
+    /// the table this builder just constructed, and by tests.
+    fn get(&self, key: &K) -> Option<&V> {
+        let bucket = slot(key, 0, self.m);
+        let d = self.g[bucket] as u64;
+        let (k, v) = self.values[slot(key, d + 1, self.r)].as_ref()?;
+        if k == key {
+            Some(v)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, PartialEq, Debug)]
+    struct TestKey(u32);
+
+    impl PerfectHashKey for TestKey {
+        fn phash(&self, level: u64) -> u64 {
+            // Same mixing function as SplitMix64 above, just applied to
+            // (key, level) instead of to a running counter -- good enough
+            // avalanche behavior for a hash function under test.
+            let mut z = self.0 as u64 ^ level.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^ (z >> 31)
+        }
+    }
+
+    #[test]
+    fn chd_round_trips_every_key() {
+        let items: Vec<(TestKey, u32)> =
+            (0..64).map(|i| (TestKey(i), i * 10)).collect();
+        let map = OwnedChdMap::build(items.clone(), 0x1234_5678).unwrap();
+        for (k, v) in &items {
+            assert_eq!(map.get(k), Some(v));
+        }
+    }
+
+    #[test]
+    fn chd_handles_empty_input() {
+        let map = OwnedChdMap::<TestKey, u32>::build(vec![], 0).unwrap();
+        assert_eq!(map.get(&TestKey(0)), None);
+    }
+
+    #[test]
+    fn chd_same_seed_is_reproducible() {
+        let items: Vec<(TestKey, u32)> =
+            (0..40).map(|i| (TestKey(i * 7), i)).collect();
+        let a = OwnedChdMap::build(items.clone(), 42).unwrap();
+        let b = OwnedChdMap::build(items, 42).unwrap();
+        assert_eq!(a.g, b.g);
+        assert_eq!(format!("{:?}", a.values), format!("{:?}", b.values));
+    }
+}
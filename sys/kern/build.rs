@@ -6,7 +6,7 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::Write;
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use serde::Deserialize;
 
 fn main() -> Result<()> {
@@ -61,11 +61,25 @@ fn generate_statics() -> Result<()> {
         ron::de::from_str(&build_util::env_var("HUBRIS_KCONFIG")?)
             .context("parsing kconfig from HUBRIS_KCONFIG")?;
 
+    validate_kconfig(&kconfig)?;
+
+    // Perfect-hash construction involves a search over displacement values,
+    // and an unseeded search can pick different (but equally valid) tables
+    // from one build to the next -- which would make the kernel binary not
+    // bit-reproducible. Derive a seed from HUBRIS_IMAGE_ID, which is already
+    // fixed per-image, so the same kconfig always yields the same tables.
+    let phash_seed = derive_phash_seed(image_id);
+
     let out = build_util::out_dir();
     let mut file =
         File::create(out.join("kconfig.rs")).context("creating kconfig.rs")?;
 
     writeln!(file, "// See build.rs for details")?;
+    writeln!(
+        file,
+        "// perfect-hash seed (derived from HUBRIS_IMAGE_ID): {:#018x}",
+        phash_seed
+    )?;
 
     writeln!(file, "#[no_mangle]")?;
     writeln!(file, "pub static HUBRIS_IMAGE_ID: u64 = {};", image_id)?;
@@ -100,6 +114,28 @@ fn generate_statics() -> Result<()> {
     }
     writeln!(file, "];")?;
 
+    // These duplicate checks already performed in `validate_kconfig`, but
+    // emitting them into the generated file means kconfig.rs is self
+    // checking even if it's regenerated by a different build.rs, or hand
+    // edited during debugging. They assert on the literal values baked into
+    // the source text above, rather than reading them back out of
+    // `HUBRIS_TASK_DESCS`: a `const` initializer can't refer to a `static`
+    // (E0013), and `HUBRIS_TASK_DESCS` has to be a `static` so the kernel can
+    // take its address.
+    for (i, task) in kconfig.tasks.iter().enumerate() {
+        writeln!(
+            file,
+            "const _: () = assert!(({} as usize) < abi::NUM_PRIORITIES); \
+            // task {}",
+            task.priority, i
+        )?;
+    }
+    writeln!(
+        file,
+        "const _: () = assert!({} == HUBRIS_TASK_COUNT);",
+        kconfig.tasks.len()
+    )?;
+
     writeln!(
         file,
         "static mut HUBRIS_TASK_TABLE_SPACE: \
@@ -145,7 +181,7 @@ fn generate_statics() -> Result<()> {
     // The second table allows for efficient implementation of `irq_control`,
     // where a task enables or disables one or more IRQS based on notification
     // masks.
-    let irq_task_map = kconfig
+    let irq_task_items = kconfig
         .irqs
         .iter()
         .map(|irq| (irq.irq, irq.owner))
@@ -155,7 +191,7 @@ fn generate_statics() -> Result<()> {
     for irq in &kconfig.irqs {
         per_task_irqs.entry(irq.owner).or_default().push(irq.irq)
     }
-    let task_irq_map = per_task_irqs.into_iter().collect::<Vec<_>>();
+    let task_irq_items = per_task_irqs.into_iter().collect::<Vec<_>>();
 
     use abi::{InterruptNum, InterruptOwner};
     let fmt_irq_task = |v: Option<&(InterruptNum, InterruptOwner)>| {
@@ -185,12 +221,49 @@ fn generate_statics() -> Result<()> {
         }
     };
 
+    // Metadata about whichever lookup shape each table ended up using,
+    // gathered as we go so it can be serialized into `.hubris_kconfig`
+    // below without duplicating the table-selection logic.
+    let mut irq_task_section = SectionTable::default();
+    let mut task_irq_section = SectionTable::default();
+
     let target = build_util::target();
     if target.starts_with("thumbv6m") {
-        let task_irq_map = phash_gen::OwnedSortedList::build(task_irq_map)
-            .context("building task-to-IRQ map")?;
-        let irq_task_map = phash_gen::OwnedSortedList::build(irq_task_map)
-            .context("building IRQ-to-task map")?;
+        let task_irq_map =
+            phash_gen::OwnedSortedList::build(task_irq_items, phash_seed)
+                .context("building task-to-IRQ map")?;
+        let irq_task_map =
+            phash_gen::OwnedSortedList::build(irq_task_items, phash_seed)
+                .context("building IRQ-to-task map")?;
+
+        irq_task_section = SectionTable {
+            kind: SECTION_KIND_SORTED,
+            m: 0,
+            r: 0,
+            g: vec![],
+            bucket_lens: vec![],
+            irq_task_values: irq_task_map
+                .values
+                .iter()
+                .cloned()
+                .map(Some)
+                .collect(),
+            task_irq_values: vec![],
+        };
+        task_irq_section = SectionTable {
+            kind: SECTION_KIND_SORTED,
+            m: 0,
+            r: 0,
+            g: vec![],
+            bucket_lens: vec![],
+            irq_task_values: vec![],
+            task_irq_values: task_irq_map
+                .values
+                .iter()
+                .cloned()
+                .map(Some)
+                .collect(),
+        };
 
         // Generate text for the Interrupt and InterruptSet tables stored in the
         // PerfectHashes
@@ -224,9 +297,26 @@ pub const HUBRIS_TASK_IRQ_LOOKUP: SortedList::<abi::InterruptOwner, &'static [ab
         || target.starts_with("thumbv7em")
         || target.starts_with("thumbv8m")
     {
-        let nested_import = if let Ok(task_irq_map) =
-            phash_gen::OwnedPerfectHashMap::build(task_irq_map.clone())
+        let mut perfect_hash_map_imported = false;
+        let mut nested_map_imported = false;
+        let mut chd_map_imported = false;
+        if let Ok(task_irq_map) =
+            phash_gen::OwnedPerfectHashMap::build(
+                task_irq_items.clone(),
+                phash_seed,
+            )
         {
+            assert_seed_reproducible(
+                "task-to-IRQ perfect hash (flat)",
+                phash_seed,
+                &task_irq_map,
+                || {
+                    phash_gen::OwnedPerfectHashMap::build(
+                        task_irq_items.clone(),
+                        phash_seed,
+                    )
+                },
+            )?;
             let task_irq_value = task_irq_map
                 .values
                 .iter()
@@ -242,26 +332,113 @@ pub const HUBRIS_TASK_IRQ_LOOKUP: PerfectHashMap::<'_, abi::InterruptOwner, &'st
     ],
 }};",
                 task_irq_map.m, task_irq_value)?;
-            false
+            task_irq_section = SectionTable {
+                kind: SECTION_KIND_FLAT,
+                m: task_irq_map.m as u32,
+                r: 0,
+                g: vec![],
+                bucket_lens: vec![],
+                irq_task_values: vec![],
+                task_irq_values: task_irq_map.values.clone(),
+            };
+            perfect_hash_map_imported = true;
         } else {
-            let task_irq_map =
-                phash_gen::OwnedNestedPerfectHashMap::build(task_irq_map)
-                    .context("building task-to-IRQ perfect hash")?;
-            let task_irq_value = task_irq_map
-                .values
-                .iter()
-                .map(|v| {
-                    format!(
-                        "&[\n            {}\n        ],",
-                        v.iter()
-                            .map(|o| fmt_task_irq(o.as_ref()))
-                            .collect::<Vec<String>>()
-                            .join("\n            ")
+            // Neither a flat nor a nested perfect hash is free. Build the
+            // nested map first: it's the fallback of last resort, so it
+            // needs to succeed whenever at all possible. Its `m`
+            // independent per-bucket displacement searches are a far
+            // easier combinatorial problem than CHD's single shared
+            // `r`-slot array below, so treat *its* failure as fatal.
+            let task_irq_map_nested =
+                phash_gen::OwnedNestedPerfectHashMap::build(
+                    task_irq_items.clone(),
+                    phash_seed,
+                )
+                .context("building task-to-IRQ perfect hash (nested)")?;
+            assert_seed_reproducible(
+                "task-to-IRQ perfect hash (nested)",
+                phash_seed,
+                &task_irq_map_nested,
+                || {
+                    phash_gen::OwnedNestedPerfectHashMap::build(
+                        task_irq_items.clone(),
+                        phash_seed,
                     )
-                })
-                .collect::<Vec<String>>()
-                .join("\n        ");
-            writeln!(file, "
+                },
+            )?;
+
+            // CHD only stores `g` (m entries); nested additionally stores
+            // one bucket-length entry per bucket and, at runtime, one
+            // slice (fat pointer) per bucket, so CHD is strictly cheaper
+            // whenever its build succeeds. Its single shared-array
+            // displacement search is a harder problem than nested's
+            // independent per-bucket searches, though, so don't propagate
+            // its failure with `?` -- fall back to the nested map already
+            // built and validated above.
+            match phash_gen::OwnedChdMap::build(
+                task_irq_items.clone(),
+                phash_seed,
+            ) {
+                Ok(task_irq_map_chd) => {
+                    assert_seed_reproducible(
+                        "task-to-IRQ perfect hash (chd)",
+                        phash_seed,
+                        &task_irq_map_chd,
+                        || {
+                            phash_gen::OwnedChdMap::build(
+                                task_irq_items.clone(),
+                                phash_seed,
+                            )
+                        },
+                    )?;
+                    chd_map_imported = true;
+                    let task_irq_value = task_irq_map_chd
+                        .values
+                        .iter()
+                        .map(|o| fmt_task_irq(o.as_ref()))
+                        .collect::<Vec<String>>()
+                        .join("\n        ");
+                    writeln!(file, "
+use phash::ChdMap;
+pub const HUBRIS_TASK_IRQ_LOOKUP: ChdMap::<'_, abi::InterruptOwner, &'static [abi::InterruptNum]> = ChdMap {{
+    m: {:#x},
+    r: {:#x},
+    g: &{:#x?},
+    values: &[
+        {}
+    ],
+}};",
+                        task_irq_map_chd.m,
+                        task_irq_map_chd.r,
+                        task_irq_map_chd.g,
+                        task_irq_value)?;
+                    task_irq_section = SectionTable {
+                        kind: SECTION_KIND_CHD,
+                        m: task_irq_map_chd.m as u32,
+                        r: task_irq_map_chd.r as u32,
+                        g: task_irq_map_chd.g.iter().map(|&d| d as u32).collect(),
+                        bucket_lens: vec![],
+                        irq_task_values: vec![],
+                        task_irq_values: task_irq_map_chd.values,
+                    };
+                }
+                Err(_) => {
+                    nested_map_imported = true;
+                    let task_irq_value = task_irq_map_nested
+                        .values
+                        .iter()
+                        .map(|v| {
+                            format!(
+                                "&[\n            {}\n        ],",
+                                v.iter()
+                                    .map(|o| fmt_task_irq(o.as_ref()))
+                                    .collect::<Vec<String>>()
+                                    .join("\n            ")
+                            )
+                        })
+                        .collect::<Vec<String>>()
+                        .join("\n        ");
+                    writeln!(file, "
 use phash::NestedPerfectHashMap;
 pub const HUBRIS_TASK_IRQ_LOOKUP: NestedPerfectHashMap::<abi::InterruptOwner, &'static [abi::InterruptNum]> = NestedPerfectHashMap {{
     m: {:#x},
@@ -270,14 +447,44 @@ pub const HUBRIS_TASK_IRQ_LOOKUP: NestedPerfectHashMap::<abi::InterruptOwner, &'
         {}
     ],
 }};",
-                task_irq_map.m, task_irq_map.g, task_irq_value)?;
-            true
-        };
+                        task_irq_map_nested.m, task_irq_map_nested.g, task_irq_value)?;
+                    task_irq_section = SectionTable {
+                        kind: SECTION_KIND_NESTED,
+                        m: task_irq_map_nested.m as u32,
+                        r: 0,
+                        g: task_irq_map_nested.g.iter().map(|&d| d as u32).collect(),
+                        bucket_lens: task_irq_map_nested
+                            .values
+                            .iter()
+                            .map(|bucket| bucket.len() as u32)
+                            .collect(),
+                        irq_task_values: vec![],
+                        task_irq_values: task_irq_map_nested
+                            .values
+                            .into_iter()
+                            .flatten()
+                            .collect(),
+                    };
+                }
+            }
+        }
 
-        if let Ok(irq_task_map) =
-            phash_gen::OwnedPerfectHashMap::build(irq_task_map.clone())
-        {
-            if nested_import {
+        if let Ok(irq_task_map) = phash_gen::OwnedPerfectHashMap::build(
+            irq_task_items.clone(),
+            phash_seed,
+        ) {
+            assert_seed_reproducible(
+                "IRQ-to-task perfect hash (flat)",
+                phash_seed,
+                &irq_task_map,
+                || {
+                    phash_gen::OwnedPerfectHashMap::build(
+                        irq_task_items.clone(),
+                        phash_seed,
+                    )
+                },
+            )?;
+            if !perfect_hash_map_imported {
                 writeln!(file, "use phash::PerfectHashMap;")?;
             }
             // Generate text for the Interrupt and InterruptSet tables stored in the
@@ -296,28 +503,104 @@ pub const HUBRIS_IRQ_TASK_LOOKUP: PerfectHashMap::<'_, abi::InterruptNum, abi::I
     ],
 }};",
                 irq_task_map.m, irq_task_value)?;
+            irq_task_section = SectionTable {
+                kind: SECTION_KIND_FLAT,
+                m: irq_task_map.m as u32,
+                r: 0,
+                g: vec![],
+                bucket_lens: vec![],
+                irq_task_values: irq_task_map.values.clone(),
+                task_irq_values: vec![],
+            };
         } else {
-            let irq_task_map =
-                phash_gen::OwnedNestedPerfectHashMap::build(irq_task_map)
-                    .context("building IRQ-to-task perfect hash")?;
-            if !nested_import {
-                writeln!(file, "use phash::NestedPerfectHashMap;")?;
-            }
-            let irq_task_value = irq_task_map
-                .values
-                .iter()
-                .map(|v| {
-                    format!(
-                        "&[\n            {}\n        ],",
-                        v.iter()
-                            .map(|o| fmt_irq_task(o.as_ref()))
-                            .collect::<Vec<String>>()
-                            .join("\n            ")
+            let irq_task_map_nested =
+                phash_gen::OwnedNestedPerfectHashMap::build(
+                    irq_task_items.clone(),
+                    phash_seed,
+                )
+                .context("building IRQ-to-task perfect hash (nested)")?;
+            assert_seed_reproducible(
+                "IRQ-to-task perfect hash (nested)",
+                phash_seed,
+                &irq_task_map_nested,
+                || {
+                    phash_gen::OwnedNestedPerfectHashMap::build(
+                        irq_task_items.clone(),
+                        phash_seed,
                     )
-                })
-                .collect::<Vec<String>>()
-                .join("\n        ");
-            writeln!(file, "
+                },
+            )?;
+            // See the task-to-IRQ table above: CHD is strictly cheaper than
+            // nested whenever its build succeeds, so don't propagate a CHD
+            // build failure with `?` -- fall back to the nested map
+            // already built and validated above.
+            match phash_gen::OwnedChdMap::build(
+                irq_task_items.clone(),
+                phash_seed,
+            ) {
+                Ok(irq_task_map_chd) => {
+                    assert_seed_reproducible(
+                        "IRQ-to-task perfect hash (chd)",
+                        phash_seed,
+                        &irq_task_map_chd,
+                        || {
+                            phash_gen::OwnedChdMap::build(
+                                irq_task_items.clone(),
+                                phash_seed,
+                            )
+                        },
+                    )?;
+                    if !chd_map_imported {
+                        writeln!(file, "use phash::ChdMap;")?;
+                    }
+                    let irq_task_value = irq_task_map_chd
+                        .values
+                        .iter()
+                        .map(|o| fmt_irq_task(o.as_ref()))
+                        .collect::<Vec<String>>()
+                        .join("\n        ");
+                    writeln!(file, "
+pub const HUBRIS_IRQ_TASK_LOOKUP: ChdMap::<'_, abi::InterruptNum, abi::InterruptOwner> = ChdMap {{
+    m: {:#x},
+    r: {:#x},
+    g: &{:#x?},
+    values: &[
+        {}
+    ],
+}};",
+                        irq_task_map_chd.m,
+                        irq_task_map_chd.r,
+                        irq_task_map_chd.g,
+                        irq_task_value)?;
+                    irq_task_section = SectionTable {
+                        kind: SECTION_KIND_CHD,
+                        m: irq_task_map_chd.m as u32,
+                        r: irq_task_map_chd.r as u32,
+                        g: irq_task_map_chd.g.iter().map(|&d| d as u32).collect(),
+                        bucket_lens: vec![],
+                        irq_task_values: irq_task_map_chd.values,
+                        task_irq_values: vec![],
+                    };
+                }
+                Err(_) => {
+                    if !nested_map_imported {
+                        writeln!(file, "use phash::NestedPerfectHashMap;")?;
+                    }
+                    let irq_task_value = irq_task_map_nested
+                        .values
+                        .iter()
+                        .map(|v| {
+                            format!(
+                                "&[\n            {}\n        ],",
+                                v.iter()
+                                    .map(|o| fmt_irq_task(o.as_ref()))
+                                    .collect::<Vec<String>>()
+                                    .join("\n            ")
+                            )
+                        })
+                        .collect::<Vec<String>>()
+                        .join("\n        ");
+                    writeln!(file, "
 pub const HUBRIS_IRQ_TASK_LOOKUP: NestedPerfectHashMap::<abi::InterruptNum, abi::InterruptOwner> = NestedPerfectHashMap {{
     m: {:#x},
     g: &{:#x?},
@@ -325,18 +608,661 @@ pub const HUBRIS_IRQ_TASK_LOOKUP: NestedPerfectHashMap::<abi::InterruptNum, abi:
         {}
     ],
 }};",
-                irq_task_map.m, irq_task_map.g, irq_task_value)?;
+                        irq_task_map_nested.m, irq_task_map_nested.g, irq_task_value)?;
+                    irq_task_section = SectionTable {
+                        kind: SECTION_KIND_NESTED,
+                        m: irq_task_map_nested.m as u32,
+                        r: 0,
+                        g: irq_task_map_nested.g.iter().map(|&d| d as u32).collect(),
+                        bucket_lens: irq_task_map_nested
+                            .values
+                            .iter()
+                            .map(|bucket| bucket.len() as u32)
+                            .collect(),
+                        irq_task_values: irq_task_map_nested
+                            .values
+                            .into_iter()
+                            .flatten()
+                            .collect(),
+                        task_irq_values: vec![],
+                    };
+                }
+            }
         }
     } else {
         panic!("Don't know the target {}", target);
     }
 
+    emit_kconfig_section(
+        &mut file,
+        image_id,
+        &kconfig,
+        &irq_task_section,
+        &task_irq_section,
+    )?;
+
+    write_size_report(&out, &kconfig, &irq_task_section, &task_irq_section)?;
+
+    Ok(())
+}
+
+/// Writes `kconfig-sizes.json` to `out_dir`, reporting the actual flash and
+/// RAM costs `generate_statics` bakes into the image where this build
+/// script can compute them, plus entry counts and `.hubris_kconfig`
+/// encoding sizes for the IRQ lookup tables. Task and IRQ counts only grow
+/// over the life of a product, so a higher-level build step can diff this
+/// against a configured RAM/flash budget and fail the build before a
+/// change actually blows it, rather than after.
+fn write_size_report(
+    out_dir: &std::path::Path,
+    kconfig: &KernelConfig,
+    irq_task: &SectionTable,
+    task_irq: &SectionTable,
+) -> Result<()> {
+    // Flash bytes of the `HUBRIS_TASK_DESCS`/`HUBRIS_REGION_DESCS` arrays
+    // `generate_statics` actually emits above. `abi::TaskDesc` and
+    // `abi::RegionDesc` are deliberately built only from fixed-width fields
+    // (u8/u16/u32, no usize, no pointers) so that their size as measured by
+    // this host-side build script matches their size on the target --
+    // unlike `crate::task::Task` below, whose layout this build script has
+    // no way to see at all.
+    let task_descs_bytes =
+        kconfig.tasks.len() * std::mem::size_of::<abi::TaskDesc>();
+    let region_descs_bytes =
+        kconfig.regions.len() * std::mem::size_of::<abi::RegionDesc>();
+
+    // RAM reserved by `HUBRIS_REGION_TABLE_SPACE`, an array of
+    // `&'static RegionDesc` pointers, one row of `abi::REGIONS_PER_TASK`
+    // per task. Every target this build script supports (thumbv6m/7m/7em/
+    // 8m) is 32-bit, so the pointer width is fixed at 4 rather than taken
+    // from this (potentially 64-bit) build host's own pointer size.
+    const TARGET_POINTER_BYTES: usize = 4;
+    let region_table_space_bytes =
+        kconfig.tasks.len() * abi::REGIONS_PER_TASK * TARGET_POINTER_BYTES;
+
+    fn table_report(table: &SectionTable) -> String {
+        let value_count =
+            table.irq_task_values.len() + table.task_irq_values.len();
+        let value_bytes: usize = table
+            .irq_task_values
+            .iter()
+            .map(|_| 1 + 4 + 4 + 4)
+            .chain(
+                table
+                    .task_irq_values
+                    .iter()
+                    .map(|slot| match slot {
+                        Some((_, irqs)) => 1 + 4 + 4 + 4 + irqs.len() * 4,
+                        None => 1 + 4 + 4 + 4,
+                    }),
+            )
+            .sum();
+        let g_bytes = table.g.len() * 4;
+        let bucket_lens_bytes = table.bucket_lens.len() * 4;
+        format!(
+            "{{\"kind\": {}, \"m\": {}, \"r\": {}, \"g_entries\": {}, \
+            \"g_bytes\": {}, \"bucket_lens_entries\": {}, \
+            \"bucket_lens_bytes\": {}, \"value_entries\": {}, \
+            \"value_bytes\": {}}}",
+            table.kind,
+            table.m,
+            table.r,
+            table.g.len(),
+            g_bytes,
+            table.bucket_lens.len(),
+            bucket_lens_bytes,
+            value_count,
+            value_bytes,
+        )
+    }
+
+    let report = format!(
+        "{{\n  \"_note\": \"task_table/region_table report the flash bytes \
+        of the generated abi::TaskDesc/RegionDesc arrays and the RAM \
+        reserved by HUBRIS_REGION_TABLE_SPACE's pointer table; \
+        HUBRIS_TASK_TABLE_SPACE's RAM is sized by crate::task::Task, which \
+        this build script cannot see the layout of, and so is NOT \
+        included here -- budget it separately\",\n  \
+        \"task_table\": {{\"count\": {}, \"flash_bytes\": {}}},\n  \
+        \"region_table\": {{\"count\": {}, \"flash_bytes\": {}, \
+        \"ram_bytes\": {}}},\n  \
+        \"irq_task_lookup\": {},\n  \
+        \"task_irq_lookup\": {}\n}}\n",
+        kconfig.tasks.len(),
+        task_descs_bytes,
+        kconfig.regions.len(),
+        region_descs_bytes,
+        region_table_space_bytes,
+        table_report(irq_task),
+        table_report(task_irq),
+    );
+
+    std::fs::write(out_dir.join("kconfig-sizes.json"), report)
+        .context("writing kconfig-sizes.json")?;
+
     Ok(())
 }
 
+// Format of the `.hubris_kconfig` section emitted by `emit_kconfig_section`.
+// Bump `SECTION_FORMAT_VERSION` any time the layout below changes; readers
+// should refuse anything with a version they don't recognize rather than
+// guessing.
+const SECTION_MAGIC: u32 = 0x4859_4243; // "HYBC", written little-endian on disk as "CBYH"
+// Version 1 shipped the task/region/IRQ tables with a flat or nested perfect
+// hash only. Version 2 added the `r` field to each table so a CHD-backed
+// table (see OwnedChdMap) can record its secondary modulus alongside `m`.
+// Version 3 added, for `SECTION_KIND_NESTED` tables only, the per-bucket
+// length array right after `g`: without it, a reader has no way to split
+// the flattened value array back into the buckets `NestedPerfectHashMap`
+// actually looks up.
+const SECTION_FORMAT_VERSION: u32 = 3;
+
+const SECTION_KIND_SORTED: u8 = 0;
+const SECTION_KIND_FLAT: u8 = 1;
+const SECTION_KIND_NESTED: u8 = 2;
+const SECTION_KIND_CHD: u8 = 3;
+
+/// The perfect-hash parameters and slot contents for one of the two IRQ
+/// lookup tables, captured from whichever of `phash_gen`'s builders
+/// `generate_statics` ended up using, so they can be serialized alongside
+/// the task and region tables.
+///
+/// `r` is only meaningful for `SECTION_KIND_CHD`, where lookup is
+/// `slot = h_{g[h0(key) mod m]}(key) mod r`; the other kinds size their
+/// value array from `m` (or don't need a modulus at all).
+///
+/// `bucket_lens` is only meaningful for `SECTION_KIND_NESTED`: each bucket
+/// has its own inner table, so `NestedPerfectHashMap::get`'s
+/// `slot(key, g[bucket] + 1, inner.len())` needs that bucket's inner
+/// length, not just `m`. `irq_task_values`/`task_irq_values` are stored
+/// flattened across all `m` buckets in bucket order, and `bucket_lens`
+/// records where one bucket's slice ends and the next one's begins.
+#[derive(Default)]
+struct SectionTable {
+    kind: u8,
+    m: u32,
+    r: u32,
+    g: Vec<u32>,
+    bucket_lens: Vec<u32>,
+    irq_task_values: Vec<Option<(abi::InterruptNum, abi::InterruptOwner)>>,
+    task_irq_values: Vec<Option<(abi::InterruptOwner, Vec<abi::InterruptNum>)>>,
+}
+
+/// Serializes the task table, region table, and both IRQ lookup tables into
+/// a small, versioned, little-endian blob and emits it into a genuinely
+/// unallocated `.hubris_kconfig` section in `kconfig.rs`.
+///
+/// This is a second, independent encoding of the same data already emitted
+/// as Rust statics above: those exist for the kernel to use directly, this
+/// exists so offline tools (debuggers, humility, flash-layout checkers) can
+/// recover the task/region/IRQ tables -- including enough of the
+/// perfect-hash parameters to replicate an `irq -> owning task` lookup --
+/// straight from the ELF file, without parsing DWARF or running kernel
+/// code.
+///
+/// The blob is emitted with `core::arch::global_asm!` rather than a
+/// `#[link_section = ".hubris_kconfig"] static`, specifically so it costs
+/// no flash or RAM: an ordinary `#[link_section]` data static is still
+/// `SHF_ALLOC` and lands in a `PT_LOAD` segment unless something downstream
+/// (a linker script, a post-link strip step) explicitly marks that output
+/// section `(NOLOAD)` -- and nothing in this tree does. A `.section` asm
+/// directive with an empty flags string, by contrast, produces a
+/// `SHT_PROGBITS` section with no `SHF_ALLOC` bit at all, so it's never
+/// loaded regardless of how the rest of the image is linked.
+fn emit_kconfig_section(
+    file: &mut File,
+    image_id: u64,
+    kconfig: &KernelConfig,
+    irq_task: &SectionTable,
+    task_irq: &SectionTable,
+) -> Result<()> {
+    let mut blob = vec![];
+    blob.extend_from_slice(&SECTION_MAGIC.to_le_bytes());
+    blob.extend_from_slice(&SECTION_FORMAT_VERSION.to_le_bytes());
+    blob.extend_from_slice(&image_id.to_le_bytes());
+    blob.extend_from_slice(&(kconfig.tasks.len() as u32).to_le_bytes());
+    blob.extend_from_slice(&(kconfig.regions.len() as u32).to_le_bytes());
+    blob.extend_from_slice(&(kconfig.irqs.len() as u32).to_le_bytes());
+
+    for table in [irq_task, task_irq] {
+        blob.push(table.kind);
+        blob.extend_from_slice(&table.m.to_le_bytes());
+        blob.extend_from_slice(&table.r.to_le_bytes());
+        blob.extend_from_slice(&(table.g.len() as u32).to_le_bytes());
+        for d in &table.g {
+            blob.extend_from_slice(&d.to_le_bytes());
+        }
+        // Only `SECTION_KIND_NESTED` has more than one bucket's worth of
+        // values, so only it needs bucket boundaries; every other kind
+        // writes a zero-length array here.
+        blob.extend_from_slice(&(table.bucket_lens.len() as u32).to_le_bytes());
+        for len in &table.bucket_lens {
+            blob.extend_from_slice(&len.to_le_bytes());
+        }
+    }
+
+    for task in &kconfig.tasks {
+        blob.extend_from_slice(&task.entry_point.to_le_bytes());
+        blob.extend_from_slice(&task.initial_stack.to_le_bytes());
+        blob.push(task.priority);
+        blob.extend_from_slice(&task.index.to_le_bytes());
+        blob.extend_from_slice(&task.flags.bits().to_le_bytes());
+        blob.extend_from_slice(&(task.regions.len() as u32).to_le_bytes());
+        for &region in &task.regions {
+            blob.extend_from_slice(&(region as u32).to_le_bytes());
+        }
+    }
+
+    for region in &kconfig.regions {
+        blob.extend_from_slice(&region.base.to_le_bytes());
+        blob.extend_from_slice(&region.size.to_le_bytes());
+        blob.extend_from_slice(&region.attributes.bits().to_le_bytes());
+    }
+
+    blob.extend_from_slice(&(irq_task.irq_task_values.len() as u32).to_le_bytes());
+    for slot in &irq_task.irq_task_values {
+        match slot {
+            Some((irq, owner)) => {
+                blob.push(1);
+                blob.extend_from_slice(&irq.0.to_le_bytes());
+                blob.extend_from_slice(&(owner.task as u32).to_le_bytes());
+                blob.extend_from_slice(&owner.notification.to_le_bytes());
+            }
+            None => blob.extend_from_slice(&[0; 1 + 4 + 4 + 4]),
+        }
+    }
+
+    blob.extend_from_slice(&(task_irq.task_irq_values.len() as u32).to_le_bytes());
+    for slot in &task_irq.task_irq_values {
+        match slot {
+            Some((owner, irqs)) => {
+                blob.push(1);
+                blob.extend_from_slice(&(owner.task as u32).to_le_bytes());
+                blob.extend_from_slice(&owner.notification.to_le_bytes());
+                blob.extend_from_slice(&(irqs.len() as u32).to_le_bytes());
+                for irq in irqs {
+                    blob.extend_from_slice(&irq.0.to_le_bytes());
+                }
+            }
+            None => blob.extend_from_slice(&[0; 1 + 4 + 4 + 4]),
+        }
+    }
+
+    // `"",@progbits` (empty flags) is what actually makes this non-alloc:
+    // an ordinary `static` with `#[link_section]` is always `SHF_ALLOC`
+    // regardless of how the section name is spelled.
+    writeln!(file, "core::arch::global_asm!(r#\"")?;
+    writeln!(file, "    .pushsection .hubris_kconfig, \"\", @progbits")?;
+    writeln!(file, "    .balign 4")?;
+    writeln!(file, "    .global HUBRIS_KCONFIG_SECTION")?;
+    writeln!(file, "HUBRIS_KCONFIG_SECTION:")?;
+    for chunk in blob.chunks(16) {
+        let line = chunk
+            .iter()
+            .map(|b| format!("{:#04x}", b))
+            .collect::<Vec<_>>()
+            .join(", ");
+        writeln!(file, "    .byte {}", line)?;
+    }
+    writeln!(file, "    .popsection")?;
+    writeln!(file, "\"#);")?;
+
+    Ok(())
+}
+
+/// Turns `image_id` into a seed for `phash_gen`'s perfect-hash search.
+///
+/// `image_id` is already fixed per-image, but xor-folding it against an
+/// arbitrary constant keeps the seed from just being the image ID itself,
+/// in case `phash_gen`'s search has low-order-bit patterns that correlate
+/// with it.
+fn derive_phash_seed(image_id: u64) -> u64 {
+    image_id ^ 0x9E37_79B9_7F4A_7C15
+}
+
+/// Re-runs a perfect-hash builder with the same seed and bails out if it
+/// doesn't reproduce `first`, the table that's actually about to be baked
+/// into `kconfig.rs`. `phash_gen`'s search is supposed to be a pure
+/// function of its inputs and seed, but if that ever regresses, this turns
+/// it into a build failure instead of a kernel image that silently stops
+/// being bit-reproducible.
+fn assert_seed_reproducible<T: std::fmt::Debug>(
+    label: &str,
+    seed: u64,
+    first: &T,
+    rebuild: impl FnOnce() -> Result<T>,
+) -> Result<()> {
+    let second = rebuild()?;
+    if format!("{:?}", first) != format!("{:?}", second) {
+        bail!(
+            "{} is not reproducible: building it twice with seed {:#018x} \
+            from the same input produced two different tables",
+            label,
+            seed,
+        );
+    }
+    Ok(())
+}
+
+/// Checks `kconfig` for the kinds of mistakes that would otherwise produce
+/// silent faults at runtime -- two tasks owning the same interrupt, a task
+/// referencing a region index that doesn't exist, overlapping regions, a
+/// stack that isn't actually inside an owned region, and so on.
+///
+/// All violations are collected and reported together, rather than bailing
+/// out on the first one, so a single build gives a complete picture of
+/// what's wrong with the kconfig instead of a slow one-at-a-time grind.
+fn validate_kconfig(kconfig: &KernelConfig) -> Result<()> {
+    let mut errors = vec![];
+
+    for (i, task) in kconfig.tasks.iter().enumerate() {
+        // `task.regions` is `[u16; abi::REGIONS_PER_TASK]`, a fixed-size
+        // array, so `.len()` is always exactly `REGIONS_PER_TASK` -- the
+        // invariant actually worth checking is that every entry in it is a
+        // valid index into `kconfig.regions`.
+        let mut any_region_out_of_range = false;
+        for (slot, &r) in task.regions.iter().enumerate() {
+            if kconfig.regions.get(r as usize).is_none() {
+                any_region_out_of_range = true;
+                errors.push(format!(
+                    "task {} region slot {} references region index {}, \
+                    but kconfig.regions only has {} entries",
+                    i,
+                    slot,
+                    r,
+                    kconfig.regions.len(),
+                ));
+            }
+        }
+
+        if task.priority as usize >= abi::NUM_PRIORITIES {
+            errors.push(format!(
+                "task {} has priority {}, but only priorities 0..{} exist",
+                i,
+                task.priority,
+                abi::NUM_PRIORITIES,
+            ));
+        }
+
+        // Skip this check entirely if any region index was already reported
+        // above -- `None => false` below would otherwise also report the
+        // stack as "not in any owned region", which is a confusing echo of
+        // the real problem rather than a second one.
+        if any_region_out_of_range {
+            continue;
+        }
+
+        let stack_in_owned_region = task.regions.iter().any(|&r| {
+            let region = match kconfig.regions.get(r as usize) {
+                Some(region) => region,
+                None => return false,
+            };
+            // Widen to u64: a region can legitimately reach the top of the
+            // 32-bit address space (e.g. the ARMv7-M System/PPB region at
+            // 0xE000_0000, size 0x2000_0000), and `base + size` in u32 would
+            // overflow and panic on exactly those valid configs.
+            let end = region.base as u64 + region.size as u64;
+            region.attributes.contains(abi::RegionAttributes::WRITE)
+                && task.initial_stack as u64 >= region.base as u64
+                && task.initial_stack as u64 <= end
+        });
+        if !stack_in_owned_region {
+            errors.push(format!(
+                "task {}'s initial_stack {:#010x} is not inside any of its \
+                owned read-write regions",
+                i, task.initial_stack,
+            ));
+        }
+    }
+
+    for i in 0..kconfig.regions.len() {
+        for j in (i + 1)..kconfig.regions.len() {
+            let a = &kconfig.regions[i];
+            let b = &kconfig.regions[j];
+
+            // Identical regions (e.g. two tasks sharing a peripheral window
+            // or a pooled RAM region) and zero-size regions are deliberate,
+            // not overlap bugs -- only flag pairs that actually carve up
+            // conflicting address ranges.
+            if (a.base, a.size) == (b.base, b.size) || a.size == 0 || b.size == 0
+            {
+                continue;
+            }
+
+            // Widen to u64: a region's end can legitimately be 0x1_0000_0000
+            // (e.g. the ARMv7-M System/PPB region at 0xE000_0000, size
+            // 0x2000_0000), which overflows u32.
+            let a_end = a.base as u64 + a.size as u64;
+            let b_end = b.base as u64 + b.size as u64;
+            if (a.base as u64) < b_end && (b.base as u64) < a_end {
+                errors.push(format!(
+                    "region {} ({:#010x}..{:#010x}) overlaps region {} \
+                    ({:#010x}..{:#010x})",
+                    i, a.base, a_end, j, b.base, b_end,
+                ));
+            }
+        }
+    }
+
+    let mut irq_owner: HashMap<u32, usize> = HashMap::new();
+    for (i, irq) in kconfig.irqs.iter().enumerate() {
+        if let Some(&prev) = irq_owner.get(&irq.irq.0) {
+            errors.push(format!(
+                "irq {} is claimed by both task {} and task {}",
+                irq.irq.0, prev, irq.owner.task,
+            ));
+        } else {
+            irq_owner.insert(irq.irq.0, irq.owner.task as usize);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        bail!(
+            "kconfig validation failed with {} problem(s):\n{}",
+            errors.len(),
+            errors
+                .iter()
+                .map(|e| format!("  - {}", e))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+    }
+}
+
 #[derive(Deserialize)]
 struct KernelConfig {
     tasks: Vec<abi::TaskDesc>,
     regions: Vec<abi::RegionDesc>,
     irqs: Vec<abi::Interrupt>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(
+        base: u32,
+        size: u32,
+        attributes: abi::RegionAttributes,
+    ) -> abi::RegionDesc {
+        abi::RegionDesc {
+            base,
+            size,
+            attributes,
+        }
+    }
+
+    fn task(
+        regions: [u16; abi::REGIONS_PER_TASK],
+        initial_stack: u32,
+        priority: u8,
+    ) -> abi::TaskDesc {
+        abi::TaskDesc {
+            regions,
+            entry_point: 0,
+            initial_stack,
+            priority,
+            index: 0,
+            flags: abi::TaskFlags::empty(),
+        }
+    }
+
+    fn irq(num: u32, owner_task: u32, notification: u32) -> abi::Interrupt {
+        abi::Interrupt {
+            irq: abi::InterruptNum(num),
+            owner: abi::InterruptOwner {
+                task: owner_task,
+                notification,
+            },
+        }
+    }
+
+    // Every region slot past `indices.len()` is filled in with
+    // `indices[0]`, so a fixture that passes an invalid `indices[0]` makes
+    // every slot of the task invalid.
+    fn regions_for(indices: &[u16]) -> [u16; abi::REGIONS_PER_TASK] {
+        let mut regions = [indices[0]; abi::REGIONS_PER_TASK];
+        for (slot, &r) in indices.iter().enumerate() {
+            regions[slot] = r;
+        }
+        regions
+    }
+
+    #[test]
+    fn valid_config_has_no_errors() {
+        let regions = vec![region(
+            0x2000_0000,
+            0x1000,
+            abi::RegionAttributes::READ | abi::RegionAttributes::WRITE,
+        )];
+        let tasks = vec![task(regions_for(&[0]), 0x2000_0100, 0)];
+        let kconfig = KernelConfig {
+            tasks,
+            regions,
+            irqs: vec![],
+        };
+        assert!(validate_kconfig(&kconfig).is_ok());
+    }
+
+    #[test]
+    fn bad_region_index_is_reported() {
+        let regions = vec![region(
+            0x2000_0000,
+            0x1000,
+            abi::RegionAttributes::READ | abi::RegionAttributes::WRITE,
+        )];
+        let bad_index = regions.len() as u16;
+        let tasks = vec![task(regions_for(&[bad_index]), 0x2000_0100, 0)];
+        let kconfig = KernelConfig {
+            tasks,
+            regions,
+            irqs: vec![],
+        };
+        let err = validate_kconfig(&kconfig).unwrap_err().to_string();
+        assert!(
+            err.contains("references region index"),
+            "unexpected error: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn priority_out_of_range_is_reported() {
+        let regions = vec![region(
+            0x2000_0000,
+            0x1000,
+            abi::RegionAttributes::READ | abi::RegionAttributes::WRITE,
+        )];
+        let tasks =
+            vec![task(regions_for(&[0]), 0x2000_0100, u8::MAX)];
+        let kconfig = KernelConfig {
+            tasks,
+            regions,
+            irqs: vec![],
+        };
+        let err = validate_kconfig(&kconfig).unwrap_err().to_string();
+        assert!(
+            err.contains("only priorities 0.."),
+            "unexpected error: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn stack_outside_any_owned_region_is_reported() {
+        let regions = vec![region(
+            0x2000_0000,
+            0x1000,
+            abi::RegionAttributes::READ | abi::RegionAttributes::WRITE,
+        )];
+        // Well past the end of the only region this task owns.
+        let tasks = vec![task(regions_for(&[0]), 0x3000_0000, 0)];
+        let kconfig = KernelConfig {
+            tasks,
+            regions,
+            irqs: vec![],
+        };
+        let err = validate_kconfig(&kconfig).unwrap_err().to_string();
+        assert!(
+            err.contains("is not inside any of its"),
+            "unexpected error: {}",
+            err
+        );
+    }
+
+    #[test]
+    fn overlapping_regions_are_reported() {
+        let regions = vec![
+            region(
+                0x2000_0000,
+                0x1000,
+                abi::RegionAttributes::READ | abi::RegionAttributes::WRITE,
+            ),
+            // Starts inside the first region's range -- a genuine overlap,
+            // not the identical-or-zero-size case `validate_kconfig` skips.
+            region(
+                0x2000_0800,
+                0x1000,
+                abi::RegionAttributes::READ | abi::RegionAttributes::WRITE,
+            ),
+        ];
+        let tasks = vec![
+            task(regions_for(&[0]), 0x2000_0100, 0),
+            task(regions_for(&[1]), 0x2000_0900, 1),
+        ];
+        let kconfig = KernelConfig {
+            tasks,
+            regions,
+            irqs: vec![],
+        };
+        let err = validate_kconfig(&kconfig).unwrap_err().to_string();
+        assert!(err.contains("overlaps region"), "unexpected error: {}", err);
+    }
+
+    #[test]
+    fn duplicate_irq_owner_is_reported() {
+        let regions = vec![region(
+            0x2000_0000,
+            0x1000,
+            abi::RegionAttributes::READ | abi::RegionAttributes::WRITE,
+        )];
+        let tasks = vec![
+            task(regions_for(&[0]), 0x2000_0100, 0),
+            task(regions_for(&[0]), 0x2000_0100, 1),
+        ];
+        let irqs = vec![irq(3, 0, 0b1), irq(3, 1, 0b1)];
+        let kconfig = KernelConfig {
+            tasks,
+            regions,
+            irqs,
+        };
+        let err = validate_kconfig(&kconfig).unwrap_err().to_string();
+        assert!(
+            err.contains("is claimed by both task"),
+            "unexpected error: {}",
+            err
+        );
+    }
+}